@@ -0,0 +1,201 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+
+use flate2::read::MultiGzDecoder;
+
+use crate::error::SearchError;
+
+/// An archive format whose members `--search-archives` searches like the
+/// files of a directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl ArchiveKind {
+    /// Returns the kind of archive a file is, judging by its name.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// A regular file in an archive.
+#[derive(Debug, Clone)]
+pub struct Member {
+    /// The position of the member among all the archive's entries
+    pub index: usize,
+    /// The member's path inside the archive
+    pub name: PathBuf,
+}
+
+/// Lists the regular files in the archive at `path` that `include` accepts.
+/// Only a compressed tarball has to be decompressed to do so; zip archives
+/// have a central directory, and tarball contents are skipped over.
+pub fn list_members<F>(
+    path: &Path,
+    kind: ArchiveKind,
+    include: F,
+) -> Result<Vec<Member>, SearchError>
+where
+    F: FnMut(&Path) -> bool,
+{
+    let file = BufReader::new(File::open(path).map_err(invalid_archive(path))?);
+    match kind {
+        ArchiveKind::Tar => {
+            let mut archive = tar::Archive::new(file);
+            let entries = archive.entries_with_seek().map_err(invalid_archive(path))?;
+            list_tar_members(path, entries, include)
+        }
+        ArchiveKind::TarGz => {
+            let mut archive = tar::Archive::new(MultiGzDecoder::new(file));
+            let entries = archive.entries().map_err(invalid_archive(path))?;
+            list_tar_members(path, entries, include)
+        }
+        ArchiveKind::Zip => list_zip_members(path, file, include),
+    }
+}
+
+/// Reads `members` from the archive at `path` in the order they are stored,
+/// handing each one's name and contents to `search` until it breaks.
+/// `members` must be sorted by index, as `list_members` returns them.
+pub fn read_members<F>(
+    path: &Path,
+    kind: ArchiveKind,
+    members: &[Member],
+    search: F,
+) -> Result<(), SearchError>
+where
+    F: FnMut(&Path, &mut dyn Read) -> ControlFlow<()>,
+{
+    let file = BufReader::new(File::open(path).map_err(invalid_archive(path))?);
+    match kind {
+        ArchiveKind::Tar => {
+            let mut archive = tar::Archive::new(file);
+            let entries = archive.entries_with_seek().map_err(invalid_archive(path))?;
+            read_tar_members(path, entries, members, search)
+        }
+        ArchiveKind::TarGz => {
+            let mut archive = tar::Archive::new(MultiGzDecoder::new(file));
+            let entries = archive.entries().map_err(invalid_archive(path))?;
+            read_tar_members(path, entries, members, search)
+        }
+        ArchiveKind::Zip => read_zip_members(path, file, members, search),
+    }
+}
+
+fn list_tar_members<R, F>(
+    path: &Path,
+    entries: tar::Entries<'_, R>,
+    mut include: F,
+) -> Result<Vec<Member>, SearchError>
+where
+    R: Read,
+    F: FnMut(&Path) -> bool,
+{
+    let mut members = Vec::new();
+    for (index, entry) in entries.enumerate() {
+        let entry = entry.map_err(invalid_archive(path))?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let entry_path = entry.path().map_err(invalid_archive(path))?;
+        // Archives created from `.` name their members `./path`.
+        let name = entry_path.strip_prefix(".").unwrap_or(&entry_path);
+        if include(name) {
+            members.push(Member {
+                index,
+                name: name.to_path_buf(),
+            });
+        }
+    }
+    Ok(members)
+}
+
+fn read_tar_members<R, F>(
+    path: &Path,
+    entries: tar::Entries<'_, R>,
+    members: &[Member],
+    mut search: F,
+) -> Result<(), SearchError>
+where
+    R: Read,
+    F: FnMut(&Path, &mut dyn Read) -> ControlFlow<()>,
+{
+    let mut members = members.iter().peekable();
+    for (index, entry) in entries.enumerate() {
+        let Some(member) = members.next_if(|member| member.index == index) else {
+            if members.peek().is_none() {
+                break;
+            }
+            continue;
+        };
+        let mut entry = entry.map_err(invalid_archive(path))?;
+        if search(&member.name, &mut entry).is_break() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn list_zip_members<F>(
+    path: &Path,
+    file: BufReader<File>,
+    mut include: F,
+) -> Result<Vec<Member>, SearchError>
+where
+    F: FnMut(&Path) -> bool,
+{
+    let mut archive = zip::ZipArchive::new(file).map_err(invalid_archive(path))?;
+    let mut members = Vec::new();
+    for index in 0..archive.len() {
+        let entry = archive.by_index_raw(index).map_err(invalid_archive(path))?;
+        if !entry.is_file() {
+            continue;
+        }
+        let name = PathBuf::from(entry.name().map_err(invalid_archive(path))?.as_ref());
+        if include(&name) {
+            members.push(Member { index, name });
+        }
+    }
+    Ok(members)
+}
+
+fn read_zip_members<F>(
+    path: &Path,
+    file: BufReader<File>,
+    members: &[Member],
+    mut search: F,
+) -> Result<(), SearchError>
+where
+    F: FnMut(&Path, &mut dyn Read) -> ControlFlow<()>,
+{
+    let mut archive = zip::ZipArchive::new(file).map_err(invalid_archive(path))?;
+    for member in members {
+        let mut entry = archive
+            .by_index(member.index)
+            .map_err(invalid_archive(path))?;
+        if search(&member.name, &mut entry).is_break() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Returns a function that replaces an error reading the archive at `path`
+/// with one naming the archive.
+fn invalid_archive<E>(path: &Path) -> impl Fn(E) -> SearchError + '_ {
+    move |_| SearchError::InvalidArchive(path.display().to_string())
+}
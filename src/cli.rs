@@ -1,13 +1,291 @@
 use std::path::PathBuf;
 
+use encoding_rs::Encoding;
 use structopt::StructOpt;
 
+use crate::error::SearchError;
+use crate::printer::SortBy;
+use crate::worker::BinaryMode;
+
+/// The largest `--queue-capacity` accepted, as the worklist allocates a slot
+/// for every job it can hold up front.
+const MAX_QUEUE_CAPACITY: usize = 1 << 20;
+
 #[derive(StructOpt)]
 pub struct Cli {
     /// The search term
-    pub search_term: String,
+    #[structopt(required_unless_one = &["regexp", "file", "type-list"])]
+    pub search_term: Option<String>,
 
     /// The directory to search in
-    #[structopt(parse(from_os_str), default_value = ".")]
-    pub search_dir: PathBuf,
+    #[structopt(parse(from_os_str))]
+    pub search_dir: Option<PathBuf>,
+
+    /// A regular expression to search for; may be repeated. When patterns are
+    /// given with `-e` or `-f`, the first positional argument is taken as the
+    /// directory to search in. Unlike the positional search term, it may
+    /// start with `-`.
+    #[structopt(
+        short = "e",
+        long = "regexp",
+        number_of_values = 1,
+        allow_hyphen_values = true
+    )]
+    pub regexp: Vec<String>,
+
+    /// Read patterns from a file, one per line; may be repeated
+    #[structopt(short = "f", long = "file", number_of_values = 1, parse(from_os_str))]
+    pub file: Vec<PathBuf>,
+
+    /// Treat the patterns given with `-e` and `-f` as literal strings
+    #[structopt(short = "F", long = "fixed-strings")]
+    pub fixed_strings: bool,
+
+    /// Match case-insensitively
+    #[structopt(short = "i", long = "ignore-case")]
+    pub ignore_case: bool,
+
+    /// Match case-insensitively unless a pattern contains an uppercase letter
+    #[structopt(short = "S", long = "smart-case")]
+    pub smart_case: bool,
+
+    /// Only show matches surrounded by word boundaries
+    #[structopt(short = "w", long = "word-regexp")]
+    pub word_regexp: bool,
+
+    /// Only show matches that span the entire line; takes precedence over `-w`
+    #[structopt(short = "x", long = "line-regexp")]
+    pub line_regexp: bool,
+
+    /// Show lines that do not match instead of lines that do
+    #[structopt(short = "v", long = "invert-match")]
+    pub invert_match: bool,
+
+    /// Only list the files in which nothing matched
+    #[structopt(short = "L", long = "files-without-match")]
+    pub files_without_match: bool,
+
+    /// Search binary files, but only report whether they match instead of
+    /// printing their lines
+    #[structopt(long = "binary", conflicts_with = "text")]
+    pub binary: bool,
+
+    /// Search binary files as if they were text
+    #[structopt(short = "a", long = "text")]
+    pub text: bool,
+
+    /// Transcode files from this encoding, e.g. `utf-16le`, `latin1` or
+    /// `shift_jis`, before searching. Files starting with a BOM are always
+    /// transcoded from the encoding it names.
+    #[structopt(short = "E", long = "encoding", value_name = "ENCODING")]
+    pub encoding: Option<String>,
+
+    /// Search the decompressed contents of gzip, bzip2, xz, zstd and lz4
+    /// files, recognised by their magic number or extension
+    #[structopt(short = "z", long = "search-zip")]
+    pub search_zip: bool,
+
+    /// Search the files inside .tar, .tar.gz and .zip archives, reported as
+    /// `archive.zip!/path/in/archive`
+    #[structopt(long = "search-archives")]
+    pub search_archives: bool,
+
+    /// Show NUM lines after each match
+    #[structopt(short = "A", long = "after-context", value_name = "NUM")]
+    pub after_context: Option<usize>,
+
+    /// Show NUM lines before each match
+    #[structopt(short = "B", long = "before-context", value_name = "NUM")]
+    pub before_context: Option<usize>,
+
+    /// Show NUM lines before and after each match
+    #[structopt(short = "C", long = "context", value_name = "NUM")]
+    pub context: Option<usize>,
+
+    /// Sort results by file: path, modified, accessed, created or none. Line
+    /// order within each file is kept; any order but none waits for the
+    /// search to finish before printing.
+    #[structopt(long = "sort", value_name = "SORTBY", possible_values = SortBy::VARIANTS)]
+    pub sort: Option<SortBy>,
+
+    /// Sort results by file in reverse order; see `--sort`
+    #[structopt(
+        long = "sortr",
+        value_name = "SORTBY",
+        possible_values = SortBy::VARIANTS,
+        conflicts_with = "sort"
+    )]
+    pub sortr: Option<SortBy>,
+
+    /// Don't respect .gitignore, .ignore, .mgrepignore, .git/info/exclude or
+    /// the global git excludes file
+    #[structopt(long = "no-ignore")]
+    pub no_ignore: bool,
+
+    /// Search hidden files and directories
+    #[structopt(long = "hidden")]
+    pub hidden: bool,
+
+    /// Only search paths matching this glob, or skip them if it starts with
+    /// `!`; may be repeated, and later globs take precedence
+    #[structopt(
+        short = "g",
+        long = "glob",
+        number_of_values = 1,
+        allow_hyphen_values = true
+    )]
+    pub glob: Vec<String>,
+
+    /// Only search files of this type, e.g. `rust` or `py`; may be repeated
+    #[structopt(short = "t", long = "type", number_of_values = 1)]
+    pub file_type: Vec<String>,
+
+    /// Don't search files of this type; may be repeated
+    #[structopt(short = "T", long = "type-not", number_of_values = 1)]
+    pub type_not: Vec<String>,
+
+    /// Define a file type or add a glob to an existing one, as `name:glob`;
+    /// may be repeated
+    #[structopt(long = "type-add", number_of_values = 1)]
+    pub type_add: Vec<String>,
+
+    /// Show every known file type and its globs, then exit
+    #[structopt(long = "type-list")]
+    pub type_list: bool,
+
+    /// Limit directory traversal to NUM levels; 1 searches only the files
+    /// directly inside the search directory
+    #[structopt(long = "max-depth", value_name = "NUM")]
+    pub max_depth: Option<usize>,
+
+    /// Don't descend into directories on other file systems, such as network
+    /// or FUSE mounts (Unix only)
+    #[structopt(long = "one-file-system")]
+    pub one_file_system: bool,
+
+    /// Follow symbolic links instead of skipping them. Loops are detected
+    /// and reported on Unix.
+    #[structopt(long = "follow")]
+    pub follow: bool,
+
+    /// Search files with NUM threads; defaults to the number of CPUs, and is
+    /// at least 1
+    #[structopt(short = "j", long = "threads", value_name = "NUM")]
+    pub threads: Option<usize>,
+
+    /// Discover files with NUM threads; defaults to the number of search
+    /// threads, and is at least 1
+    #[structopt(long = "discovery-threads", value_name = "NUM")]
+    pub discovery_threads: Option<usize>,
+
+    /// The most files that may wait to be searched before discovery pauses
+    /// for the workers to catch up; at most 1048576
+    #[structopt(
+        long = "queue-capacity",
+        value_name = "NUM",
+        default_value = "4096",
+        parse(try_from_str = parse_queue_capacity)
+    )]
+    pub queue_capacity: usize,
+
+    /// Print statistics about the search to stderr once it is done
+    #[structopt(long = "stats")]
+    pub stats: bool,
+}
+
+impl Cli {
+    /// Returns true if the patterns come from `-e/--regexp` or `-f/--file`
+    /// rather than the positional search term.
+    pub fn has_pattern_flags(&self) -> bool {
+        !self.regexp.is_empty() || !self.file.is_empty()
+    }
+
+    /// Returns the directory to search in, accounting for the search term
+    /// being replaced by `-e/--regexp` or `-f/--file`.
+    pub fn search_dir(&self) -> PathBuf {
+        let dir = if self.has_pattern_flags() {
+            self.search_term.as_ref().map(PathBuf::from)
+        } else {
+            self.search_dir.clone()
+        };
+        dir.unwrap_or_else(|| PathBuf::from("."))
+    }
+
+    /// Returns the number of lines to show before each match.
+    pub fn before_context(&self) -> usize {
+        self.before_context.or(self.context).unwrap_or(0)
+    }
+
+    /// Returns the number of lines to show after each match.
+    pub fn after_context(&self) -> usize {
+        self.after_context.or(self.context).unwrap_or(0)
+    }
+
+    /// Returns the number of threads that search files.
+    pub fn threads(&self) -> usize {
+        self.threads.unwrap_or_else(num_cpus::get).max(1)
+    }
+
+    /// Returns the number of threads that discover files.
+    pub fn discovery_threads(&self) -> usize {
+        self.discovery_threads
+            .map_or_else(|| self.threads(), |threads| threads.max(1))
+    }
+
+    /// Returns how files that look binary are handled.
+    pub fn binary_mode(&self) -> BinaryMode {
+        if self.text {
+            BinaryMode::Text
+        } else if self.binary {
+            BinaryMode::Report
+        } else {
+            BinaryMode::Skip
+        }
+    }
+
+    /// Returns the encoding named by `-E/--encoding`, if one was given.
+    pub fn encoding(&self) -> Result<Option<&'static Encoding>, SearchError> {
+        self.encoding
+            .as_ref()
+            .map(|label| {
+                Encoding::for_label(label.as_bytes())
+                    .ok_or_else(|| SearchError::UnknownEncoding(label.clone()))
+            })
+            .transpose()
+    }
+
+    /// Returns the sort order and whether it is reversed.
+    pub fn sort(&self) -> (SortBy, bool) {
+        match (self.sort, self.sortr) {
+            (_, Some(sort)) => (sort, true),
+            (Some(sort), None) => (sort, false),
+            (None, None) => (SortBy::None, false),
+        }
+    }
+}
+
+/// Parses `--queue-capacity`, rejecting capacities too large to allocate.
+fn parse_queue_capacity(value: &str) -> Result<usize, String> {
+    let capacity: usize = value.parse().map_err(|error| format!("{}", error))?;
+    if capacity > MAX_QUEUE_CAPACITY {
+        return Err(format!("must be at most {}", MAX_QUEUE_CAPACITY));
+    }
+    Ok(capacity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue_capacity(value: &str) -> Result<usize, structopt::clap::Error> {
+        Cli::from_iter_safe(["mgrep", "term", "--queue-capacity", value])
+            .map(|cli| cli.queue_capacity)
+    }
+
+    #[test]
+    fn queue_capacity_has_a_ceiling() {
+        assert_eq!(queue_capacity("1048576").unwrap(), MAX_QUEUE_CAPACITY);
+        assert!(queue_capacity("1048577").is_err());
+        assert!(queue_capacity("100000000000").is_err());
+    }
 }
@@ -0,0 +1,144 @@
+use std::ffi::OsStr;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// A compression format that `-z/--search-zip` searches through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+    Lz4,
+}
+
+/// The bytes each format starts with.
+const MAGIC_NUMBERS: &[(&[u8], Compression)] = &[
+    (&[0x1f, 0x8b], Compression::Gzip),
+    (b"BZh", Compression::Bzip2),
+    (&[0xfd, b'7', b'z', b'X', b'Z', 0x00], Compression::Xz),
+    (&[0x28, 0xb5, 0x2f, 0xfd], Compression::Zstd),
+    (&[0x04, 0x22, 0x4d, 0x18], Compression::Lz4),
+];
+
+/// The file extensions each format is recognised by when its magic number
+/// is not found.
+const EXTENSIONS: &[(&str, Compression)] = &[
+    ("gz", Compression::Gzip),
+    ("tgz", Compression::Gzip),
+    ("bz2", Compression::Bzip2),
+    ("tbz2", Compression::Bzip2),
+    ("xz", Compression::Xz),
+    ("txz", Compression::Xz),
+    ("zst", Compression::Zstd),
+    ("lz4", Compression::Lz4),
+];
+
+impl Compression {
+    /// Returns the format of a file from the first bytes of its contents,
+    /// or from its extension when they are not recognised.
+    pub fn detect(header: &[u8], path: &Path) -> Option<Self> {
+        let by_magic = MAGIC_NUMBERS
+            .iter()
+            .find(|(magic, _)| header.starts_with(magic))
+            .map(|&(_, compression)| compression);
+        by_magic.or_else(|| {
+            let extension = path.extension().and_then(OsStr::to_str)?;
+            EXTENSIONS
+                .iter()
+                .find(|(name, _)| extension.eq_ignore_ascii_case(name))
+                .map(|&(_, compression)| compression)
+        })
+    }
+
+    /// Wraps `reader` in a decoder that decompresses it as it is read.
+    pub fn decoder<'a, R>(self, reader: R) -> io::Result<Box<dyn Read + 'a>>
+    where
+        R: Read + 'a,
+    {
+        Ok(match self {
+            // The multi-member decoders read concatenated streams to the end,
+            // as produced by appending to a compressed log.
+            Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(reader)),
+            Compression::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(reader)),
+            Compression::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(reader)),
+            Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(reader)?),
+            Compression::Lz4 => Box::new(lz4_flex::frame::FrameDecoder::new(reader)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// Decompresses `compressed` with the decoder for the format `detect`
+    /// finds in it.
+    fn decompress(compressed: &[u8], path: &str) -> String {
+        let compression = Compression::detect(compressed, Path::new(path)).unwrap();
+        let mut contents = String::new();
+        compression
+            .decoder(compressed)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        contents
+    }
+
+    fn gzip(contents: &str) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(contents.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn detects_formats_by_magic_number() {
+        let path = Path::new("data");
+        assert_eq!(
+            Compression::detect(&gzip("x"), path),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(
+            Compression::detect(b"BZh91AY", path),
+            Some(Compression::Bzip2)
+        );
+        assert_eq!(
+            Compression::detect(&[0x28, 0xb5, 0x2f, 0xfd, 0x00], path),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(Compression::detect(b"plain text", path), None);
+    }
+
+    #[test]
+    fn detects_formats_by_extension() {
+        assert_eq!(
+            Compression::detect(b"", Path::new("log.XZ")),
+            Some(Compression::Xz)
+        );
+        assert_eq!(
+            Compression::detect(b"", Path::new("a.tbz2")),
+            Some(Compression::Bzip2)
+        );
+        assert_eq!(Compression::detect(b"", Path::new("a.txt")), None);
+    }
+
+    #[test]
+    fn magic_number_beats_extension() {
+        assert_eq!(
+            Compression::detect(&gzip("x"), Path::new("data.zst")),
+            Some(Compression::Gzip)
+        );
+    }
+
+    #[test]
+    fn gzip_round_trips() {
+        assert_eq!(decompress(&gzip("needle\n"), "data"), "needle\n");
+    }
+
+    #[test]
+    fn zstd_round_trips() {
+        let compressed = zstd::stream::encode_all("needle\n".as_bytes(), 0).unwrap();
+        assert_eq!(decompress(&compressed, "data.gz"), "needle\n");
+    }
+}
@@ -4,6 +4,26 @@ pub enum SearchError {
     IoError(std::io::Error),
     /// Represents an invalid search directory
     InvalidDir(String),
+    /// Represents a search pattern that failed to compile
+    InvalidPattern(Box<regex_automata::meta::BuildError>),
+    /// Represents a set of literal patterns too large to compile
+    InvalidPatternSet(aho_corasick::BuildError),
+    /// Represents a pattern file that could not be read
+    InvalidPatternFile(String),
+    /// Represents a glob given with `-g/--glob` that failed to compile
+    InvalidGlob(globset::Error),
+    /// Represents a file type name that is not defined
+    UnknownFileType(String),
+    /// Represents a `--type-add` definition not of the form `name:glob`
+    InvalidTypeDefinition(String),
+    /// Represents a symbolic link that leads back to one of its ancestors
+    SymlinkLoop(String),
+    /// Represents a job added after the worklist was closed
+    WorklistClosed,
+    /// Represents an encoding label that names no known encoding
+    UnknownEncoding(String),
+    /// Represents an archive that could not be read
+    InvalidArchive(String),
 }
 
 impl From<std::io::Error> for SearchError {
@@ -20,6 +40,41 @@ impl std::fmt::Display for SearchError {
             SearchError::IoError(error) => write!(f, "IO error: {}", error),
             // Provide a custom message for the invalid search directory error
             SearchError::InvalidDir(path) => write!(f, "Failed to read directory: '{}'", path),
+            // Include the regex parser's explanation of what went wrong, if there is one
+            SearchError::InvalidPattern(error) => match error.syntax_error() {
+                Some(syntax_error) => write!(f, "Invalid pattern: {}", syntax_error),
+                None => write!(f, "Invalid pattern: {}", error),
+            },
+            // Surface why the pattern automaton could not be built
+            SearchError::InvalidPatternSet(error) => write!(f, "Invalid pattern set: {}", error),
+            // Provide a custom message for the unreadable pattern file error
+            SearchError::InvalidPatternFile(path) => {
+                write!(f, "Failed to read pattern file: '{}'", path)
+            }
+            // Include the glob compiler's explanation of what went wrong
+            SearchError::InvalidGlob(error) => write!(f, "Invalid glob: {}", error),
+            // Point the user at the list of known file types
+            SearchError::UnknownFileType(name) => {
+                write!(f, "Unknown file type: '{}' (see --type-list)", name)
+            }
+            // Show the expected form of a file type definition
+            SearchError::InvalidTypeDefinition(definition) => write!(
+                f,
+                "Invalid file type definition: '{}' (expected 'name:glob')",
+                definition
+            ),
+            // Name the link that was skipped
+            SearchError::SymlinkLoop(path) => {
+                write!(f, "Skipping symbolic link loop: '{}'", path)
+            }
+            // Provide a custom message for the closed worklist error
+            SearchError::WorklistClosed => write!(f, "The worklist has already been closed"),
+            // Name the label that was not recognised
+            SearchError::UnknownEncoding(label) => write!(f, "Unknown encoding: '{}'", label),
+            // Name the archive that was skipped
+            SearchError::InvalidArchive(path) => {
+                write!(f, "Skipping unreadable archive: '{}'", path)
+            }
         }
     }
 }
@@ -0,0 +1,91 @@
+use std::path::Path;
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::error::SearchError;
+
+/// Include and exclude globs given with `-g/--glob`, compiled into a single
+/// glob set. Globs are matched against paths relative to the search root, and
+/// the last glob that matches a path decides whether it is kept.
+pub struct GlobFilter {
+    set: GlobSet,
+    /// Whether each glob in the set was negated with `!`
+    negated: Vec<bool>,
+    /// Whether any glob selects files rather than excluding them
+    has_includes: bool,
+}
+
+impl GlobFilter {
+    pub fn new(globs: &[String]) -> Result<Self, SearchError> {
+        let mut builder = GlobSetBuilder::new();
+        let mut negated = Vec::with_capacity(globs.len());
+        for glob in globs {
+            let (pattern, is_negated) = match glob.strip_prefix('!') {
+                Some(pattern) => (pattern, true),
+                None => (glob.as_str(), false),
+            };
+            builder.add(Glob::new(pattern).map_err(SearchError::InvalidGlob)?);
+            negated.push(is_negated);
+        }
+        let set = builder.build().map_err(SearchError::InvalidGlob)?;
+        let has_includes = negated.iter().any(|is_negated| !is_negated);
+        Ok(Self {
+            set,
+            negated,
+            has_includes,
+        })
+    }
+
+    /// Returns true if the path should be searched, or for a directory,
+    /// descended into. Include globs only select files, so a directory is
+    /// only skipped when an exclude glob matches it.
+    pub fn is_included(&self, path: &Path, is_dir: bool) -> bool {
+        match self.set.matches(path).last() {
+            Some(&index) => !self.negated[index],
+            None => is_dir || !self.has_includes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(globs: &[&str]) -> GlobFilter {
+        let globs: Vec<String> = globs.iter().map(|glob| glob.to_string()).collect();
+        GlobFilter::new(&globs).unwrap()
+    }
+
+    #[test]
+    fn rust_files_outside_vendor() {
+        let filter = filter(&["*.rs", "!vendor/**"]);
+        assert!(filter.is_included(Path::new("src/main.rs"), false));
+        assert!(!filter.is_included(Path::new("src/main.c"), false));
+        assert!(!filter.is_included(Path::new("vendor/lib.rs"), false));
+        assert!(filter.is_included(Path::new("src"), true));
+        assert!(!filter.is_included(Path::new("vendor/sub"), true));
+    }
+
+    #[test]
+    fn last_matching_glob_wins() {
+        let excluded_last = filter(&["*.rs", "!generated*"]);
+        assert!(!excluded_last.is_included(Path::new("generated.rs"), false));
+        let included_last = filter(&["!generated*", "*.rs"]);
+        assert!(included_last.is_included(Path::new("generated.rs"), false));
+    }
+
+    #[test]
+    fn include_globs_do_not_prune_directories() {
+        let filter = filter(&["*.rs"]);
+        assert!(filter.is_included(Path::new("src"), true));
+        assert!(filter.is_included(Path::new("docs"), true));
+        assert!(!filter.is_included(Path::new("docs"), false));
+    }
+
+    #[test]
+    fn exclude_globs_alone_keep_everything_else() {
+        let filter = filter(&["!*.log"]);
+        assert!(filter.is_included(Path::new("main.rs"), false));
+        assert!(!filter.is_included(Path::new("app.log"), false));
+    }
+}
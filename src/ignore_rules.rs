@@ -0,0 +1,147 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+
+/// Per-directory ignore files, from highest to lowest precedence.
+const IGNORE_FILES: &[&str] = &[".mgrepignore", ".ignore", ".gitignore"];
+
+/// The ignore rules in effect for a directory: the ignore files found in it,
+/// followed by the rules inherited from its parent.
+///
+/// All matchers are rooted at absolute paths, so the paths passed to
+/// `is_ignored` must be absolute too.
+pub struct IgnoreRules {
+    parent: Option<Arc<IgnoreRules>>,
+    /// Matchers for a single directory, highest precedence first
+    matchers: Vec<Gitignore>,
+}
+
+impl IgnoreRules {
+    /// Returns the rules in effect at the search root: the user's global git
+    /// excludes, then the ignore files of every ancestor of `root`.
+    pub fn for_root(root: &Path) -> Arc<Self> {
+        let (global, error) = Gitignore::global();
+        if let Some(error) = error {
+            eprintln!("Error reading global git excludes: {}", error);
+        }
+        let matchers = if global.is_empty() {
+            Vec::new()
+        } else {
+            vec![global]
+        };
+
+        let mut rules = Arc::new(Self {
+            parent: None,
+            matchers,
+        });
+        let ancestors: Vec<&Path> = root.ancestors().skip(1).collect();
+        for ancestor in ancestors.into_iter().rev() {
+            rules = rules.for_dir(ancestor);
+        }
+        rules
+    }
+
+    /// Returns the rules in effect inside `dir`, which must be a child of the
+    /// directory these rules belong to.
+    pub fn for_dir(self: &Arc<Self>, dir: &Path) -> Arc<Self> {
+        let mut matchers: Vec<Gitignore> = IGNORE_FILES
+            .iter()
+            .filter_map(|name| load(dir, &dir.join(name)))
+            .collect();
+        // Repository-local excludes apply below the repository root with the
+        // lowest precedence.
+        if let Some(exclude) = load(dir, &dir.join(".git").join("info").join("exclude")) {
+            matchers.push(exclude);
+        }
+
+        if matchers.is_empty() {
+            return Arc::clone(self);
+        }
+        Arc::new(Self {
+            parent: Some(Arc::clone(self)),
+            matchers,
+        })
+    }
+
+    /// Returns true if `path` is excluded by the closest rule that mentions
+    /// it. A negated pattern (`!pattern`) re-includes the path.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut rules = Some(self);
+        while let Some(current) = rules {
+            for matcher in &current.matchers {
+                match matcher.matched(path, is_dir) {
+                    Match::Ignore(_) => return true,
+                    Match::Whitelist(_) => return false,
+                    Match::None => {}
+                }
+            }
+            rules = current.parent.as_deref();
+        }
+        false
+    }
+}
+
+/// Loads the ignore file at `path`, with its patterns relative to `root`.
+fn load(root: &Path, path: &Path) -> Option<Gitignore> {
+    if !path.is_file() {
+        return None;
+    }
+    let mut builder = GitignoreBuilder::new(root);
+    if let Some(error) = builder.add(path) {
+        eprintln!("Error reading ignore file {}: {}", path.display(), error);
+    }
+    match builder.build() {
+        Ok(matcher) => (!matcher.is_empty()).then_some(matcher),
+        Err(error) => {
+            eprintln!("Error reading ignore file {}: {}", path.display(), error);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Rules with no global excludes or ancestors, so only the files written
+    /// by a test apply.
+    fn empty() -> Arc<IgnoreRules> {
+        Arc::new(IgnoreRules {
+            parent: None,
+            matchers: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn nested_negation_overrides_parent_rule() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let sub = root.join("sub");
+        fs::create_dir(&sub).unwrap();
+        fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(sub.join(".gitignore"), "!keep.log\n").unwrap();
+
+        let root_rules = empty().for_dir(root);
+        let sub_rules = root_rules.for_dir(&sub);
+        assert!(root_rules.is_ignored(&root.join("keep.log"), false));
+        assert!(!sub_rules.is_ignored(&sub.join("keep.log"), false));
+        assert!(sub_rules.is_ignored(&sub.join("other.log"), false));
+    }
+
+    #[test]
+    fn mgrepignore_beats_ignore_beats_gitignore() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join(".gitignore"), "*.txt\n").unwrap();
+        fs::write(root.join(".ignore"), "!b.txt\n!c.txt\n").unwrap();
+        fs::write(root.join(".mgrepignore"), "c.txt\n").unwrap();
+
+        let rules = empty().for_dir(root);
+        assert!(rules.is_ignored(&root.join("a.txt"), false));
+        assert!(!rules.is_ignored(&root.join("b.txt"), false));
+        assert!(rules.is_ignored(&root.join("c.txt"), false));
+    }
+}
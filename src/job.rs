@@ -1,15 +1,79 @@
-use std::path::PathBuf;
+use std::cmp::Ordering;
+use std::fmt;
+use std::path::{Path, PathBuf};
 
-pub struct Job {
-    path: PathBuf,
+use crate::archive::{ArchiveKind, Member};
+
+/// Something to search.
+pub enum Job {
+    /// A file on disk
+    File(PathBuf),
+    /// Members of an archive, read straight from the archive in the order
+    /// they are stored when the job is searched
+    Members {
+        archive: PathBuf,
+        kind: ArchiveKind,
+        members: Vec<Member>,
+    },
+}
+
+/// The path of a searched file: either a file on disk or a member of an
+/// archive on disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilePath {
+    Disk(PathBuf),
+    Member { archive: PathBuf, name: PathBuf },
+}
+
+impl FilePath {
+    /// Returns the file on disk that holds this file: the file itself, or
+    /// the archive it is a member of.
+    pub fn disk_path(&self) -> &Path {
+        match self {
+            FilePath::Disk(path) => path,
+            FilePath::Member { archive, .. } => archive,
+        }
+    }
+
+    /// Returns the path of the file itself, which for a member is its path
+    /// inside the archive.
+    pub fn inner_path(&self) -> &Path {
+        match self {
+            FilePath::Disk(path) => path,
+            FilePath::Member { name, .. } => name,
+        }
+    }
+
+    /// Returns the member's path inside its archive, if this is a member.
+    fn member_name(&self) -> Option<&Path> {
+        match self {
+            FilePath::Disk(_) => None,
+            FilePath::Member { name, .. } => Some(name),
+        }
+    }
 }
 
-impl Job {
-    pub fn new(path: PathBuf) -> Self {
-        Self { path }
+/// Members sort right after their archive, as if it were a directory.
+impl Ord for FilePath {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.disk_path(), self.member_name()).cmp(&(other.disk_path(), other.member_name()))
     }
+}
+
+impl PartialOrd for FilePath {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
 
-    pub fn as_path(&self) -> &PathBuf {
-        &self.path
+/// Shows a member as `archive!/path/in/archive`.
+impl fmt::Display for FilePath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FilePath::Disk(path) => write!(f, "{}", path.display()),
+            FilePath::Member { archive, name } => {
+                write!(f, "{}!/{}", archive.display(), name.display())
+            }
+        }
     }
 }
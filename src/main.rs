@@ -1,112 +1,188 @@
-use async_recursion::async_recursion;
 use cli::Cli;
-use crossbeam::channel::{unbounded, TryRecvError};
+use crossbeam::channel::bounded;
 use error::SearchError;
-use job::Job;
+use globs::GlobFilter;
+use matcher::{MatchOptions, Matcher, MultiLiteralMatcher, RegexMatcher, SubstringMatcher};
+use printer::Printer;
 use std::error::Error;
-use std::path::Path;
 use std::sync::Arc;
+use std::thread;
 use structopt::StructOpt;
-use tokio::fs;
-use worker::Worker;
+use types::TypeRegistry;
+use walker::{DiscoveryOptions, Walker};
+use worker::{SearchOptions, Worker};
 use worklist::Worklist;
 
+mod archive;
 mod cli;
+mod decompress;
 mod error;
+mod globs;
+mod ignore_rules;
 mod job;
+mod matcher;
+mod printer;
 mod result;
+mod types;
+mod walker;
 mod worker;
 mod worklist;
 
-#[async_recursion]
-async fn discover_dirs(wl: &Arc<Worklist>, dir_path: &Path) -> Result<(), SearchError> {
-    let mut entries = fs::read_dir(dir_path)
-        .await
-        .map_err(|_| SearchError::InvalidDir(dir_path.display().to_string()))?;
-
-    let mut tasks = Vec::new();
-    while let Some(entry) = entries.next_entry().await? {
-        let path = entry.path();
-        if path.is_dir() {
-            let task = async {
-                let path = Arc::new(path);
-                let wl_clone = Arc::clone(wl);
-                let path_clone = Arc::new(path.clone());
-                discover_dirs(&wl_clone, &path_clone).await?;
-                Ok::<(), SearchError>(())
-            };
-            tasks.push(task);
-        } else {
-            wl.add(Job::new(path));
-        }
+/// Number of per-file result batches that may wait for the printer before
+/// workers block.
+const RESULT_CHANNEL_CAPACITY: usize = 256;
+
+/// Collects the patterns given with `-e/--regexp` and `-f/--file`.
+fn read_patterns(args: &Cli) -> Result<Vec<String>, SearchError> {
+    let mut patterns = args.regexp.clone();
+    for path in &args.file {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|_| SearchError::InvalidPatternFile(path.display().to_string()))?;
+        patterns.extend(
+            contents
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(str::to_owned),
+        );
     }
-    for task in tasks {
-        task.await?;
+    Ok(patterns)
+}
+
+/// Builds the matcher shared by all workers from the command-line arguments.
+fn build_matcher(args: &Cli) -> Result<Arc<dyn Matcher>, SearchError> {
+    let (mut patterns, literal) = if args.has_pattern_flags() {
+        let patterns = read_patterns(args)?;
+        let literal =
+            args.fixed_strings || patterns.iter().all(|pattern| matcher::is_literal(pattern));
+        (patterns, literal)
+    } else {
+        (vec![args.search_term.clone().unwrap_or_default()], true)
+    };
+
+    let case_insensitive = args.ignore_case
+        || (args.smart_case
+            && !patterns
+                .iter()
+                .any(|pattern| matcher::has_uppercase(pattern, literal)));
+    let options = MatchOptions {
+        literal,
+        case_insensitive,
+        whole_word: args.word_regexp,
+        whole_line: args.line_regexp,
+    };
+
+    // The literal matchers only handle exact, case-sensitive substring matching.
+    if options.needs_regex() {
+        return Ok(Arc::new(RegexMatcher::new(patterns, &options)?));
     }
-    Ok(())
+    if patterns.len() == 1 {
+        return Ok(Arc::new(SubstringMatcher::new(patterns.remove(0))));
+    }
+    Ok(Arc::new(MultiLiteralMatcher::new(patterns)?))
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+/// Unwraps a result needed to start the search, or reports the error and
+/// exits.
+fn unwrap_or_exit<T>(result: Result<T, SearchError>) -> T {
+    result.unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(2);
+    })
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
     let args = Cli::from_args();
-    let search_term = args.search_term.clone();
+    let search_dir = args.search_dir();
 
-    let num_workers = num_cpus::get() - 1;
+    let mut types = TypeRegistry::default();
+    for definition in &args.type_add {
+        unwrap_or_exit(types.add(definition));
+    }
+    if args.type_list {
+        types.print();
+        return Ok(());
+    }
+
+    let matcher = unwrap_or_exit(build_matcher(&args));
+
+    let globs = (!args.glob.is_empty()).then(|| unwrap_or_exit(GlobFilter::new(&args.glob)));
+    let type_filter = (!args.file_type.is_empty() || !args.type_not.is_empty())
+        .then(|| unwrap_or_exit(types.filter(&args.file_type, &args.type_not)));
+
+    let options = SearchOptions {
+        invert_match: args.invert_match,
+        files_without_match: args.files_without_match,
+        before_context: args.before_context(),
+        after_context: args.after_context(),
+        binary: args.binary_mode(),
+        encoding: unwrap_or_exit(args.encoding()),
+        search_zip: args.search_zip,
+    };
+
+    let num_workers = args.threads();
 
-    let worklist = Arc::new(Worklist::new());
+    let worklist = Arc::new(Worklist::new(args.queue_capacity));
 
-    let mut result_receivers = Vec::new();
+    let (result_sender, result_receiver) = bounded(RESULT_CHANNEL_CAPACITY);
+    let context = options.before_context > 0 || options.after_context > 0;
+    let (sort, reverse) = args.sort();
+    let printer = thread::spawn(move || {
+        Printer::new(result_receiver, context, sort, reverse).run();
+    });
 
     let mut worker_handles = Vec::new();
     for _ in 0..num_workers {
-        let (result_sender, result_receiver) = unbounded();
-        result_receivers.push(result_receiver);
+        let result_sender = result_sender.clone();
         let worklist_clone = Arc::clone(&worklist);
-        let search_term_clone = search_term.clone();
-        let handle = tokio::spawn(async move {
-            let worker = Worker::new(search_term_clone, worklist_clone, result_sender);
-            worker.process_jobs().await;
+        let matcher_clone = Arc::clone(&matcher);
+        let handle = thread::spawn(move || {
+            let worker = Worker::new(matcher_clone, options, worklist_clone, result_sender);
+            worker.process_jobs();
         });
         worker_handles.push(handle);
     }
+    // The printer stops once the last worker drops its sender.
+    drop(result_sender);
 
+    let discovery_options = DiscoveryOptions {
+        root: search_dir,
+        no_ignore: args.no_ignore,
+        hidden: args.hidden,
+        globs,
+        types: type_filter,
+        max_depth: args.max_depth,
+        one_file_system: args.one_file_system,
+        follow_links: args.follow,
+        archives: args.search_archives,
+    };
+    let walker = Walker::new(
+        discovery_options,
+        Arc::clone(&worklist),
+        args.discovery_threads(),
+    );
     let worklist_clone = Arc::clone(&worklist);
-    tokio::spawn(async move {
-        if let Err(error) = discover_dirs(&worklist_clone, &args.search_dir).await {
+    let discovery = thread::spawn(move || {
+        if let Err(error) = walker.run() {
             eprintln!("{}", error);
             if let Some(source) = error.source() {
                 eprintln!("Caused by: {}", source);
             }
         }
-        worklist_clone.finalize(num_workers);
+        worklist_clone.close();
     });
 
+    discovery.join().map_err(|_| "discovery thread panicked")?;
     for handle in worker_handles {
-        handle.await?;
-    }
-
-    let mut results = Vec::new();
-
-    for result_receiver in result_receivers {
-        loop {
-            match result_receiver.try_recv() {
-                Ok(result_batch) => {
-                    results.extend(result_batch);
-                }
-                Err(TryRecvError::Empty) => {
-                    // println!("No more results available.");
-                    break;
-                }
-                Err(TryRecvError::Disconnected) => {
-                    // println!("The result channel has been closed.");
-                    break;
-                }
-            }
-        }
+        handle.join().map_err(|_| "worker thread panicked")?;
     }
+    printer.join().map_err(|_| "printer thread panicked")?;
 
-    for result in results {
-        result.display();
+    if args.stats {
+        eprintln!(
+            "Worklist peak depth: {} of {} jobs",
+            worklist.peak_depth(),
+            worklist.capacity()
+        );
     }
 
     Ok(())
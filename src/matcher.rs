@@ -0,0 +1,379 @@
+use aho_corasick::{AhoCorasick, MatchKind};
+use memchr::memmem;
+use regex_automata::{meta, util::syntax};
+use regex_syntax::ast::{self, Ast};
+
+use crate::error::SearchError;
+
+/// A single match within a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match {
+    /// Byte offset where the match starts
+    pub start: usize,
+    /// Byte offset just past the end of the match
+    pub end: usize,
+    /// Index of the pattern that produced the match
+    pub pattern: usize,
+}
+
+/// Options that change how patterns are interpreted.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MatchOptions {
+    /// Treat patterns as literal strings rather than regular expressions
+    pub literal: bool,
+    /// Match without regard to case, using Unicode case folding
+    pub case_insensitive: bool,
+    /// Only match where the pattern is surrounded by word boundaries
+    pub whole_word: bool,
+    /// Only match where the pattern spans the entire line
+    pub whole_line: bool,
+}
+
+impl MatchOptions {
+    /// Returns true if these options can only be honoured by the regex engine.
+    pub fn needs_regex(&self) -> bool {
+        !self.literal || self.case_insensitive || self.whole_word || self.whole_line
+    }
+}
+
+/// A strategy for finding the search pattern(s) within a line. Lines are raw
+/// bytes and need not be valid UTF-8.
+pub trait Matcher: Send + Sync {
+    /// Returns the leftmost match in the line, if any.
+    fn find(&self, line: &[u8]) -> Option<Match>;
+
+    /// Returns every non-overlapping match in the line, left to right.
+    // Searching only needs the first match; nothing but the tests asks for
+    // every span yet.
+    #[cfg_attr(not(test), allow(dead_code))]
+    fn find_all(&self, line: &[u8]) -> Vec<Match>;
+
+    /// Returns the patterns this matcher searches for, indexed by `Match::pattern`.
+    fn patterns(&self) -> &[String];
+}
+
+/// Matches a single literal term.
+pub struct SubstringMatcher {
+    term: String,
+    finder: memmem::Finder<'static>,
+}
+
+impl SubstringMatcher {
+    pub fn new(term: String) -> Self {
+        let finder = memmem::Finder::new(term.as_bytes()).into_owned();
+        Self { term, finder }
+    }
+}
+
+impl Matcher for SubstringMatcher {
+    fn find(&self, line: &[u8]) -> Option<Match> {
+        self.finder.find(line).map(|start| Match {
+            start,
+            end: start + self.term.len(),
+            pattern: 0,
+        })
+    }
+
+    fn find_all(&self, line: &[u8]) -> Vec<Match> {
+        self.finder
+            .find_iter(line)
+            .map(|start| Match {
+                start,
+                end: start + self.term.len(),
+                pattern: 0,
+            })
+            .collect()
+    }
+
+    fn patterns(&self) -> &[String] {
+        std::slice::from_ref(&self.term)
+    }
+}
+
+/// Matches one or more regular expressions, or literals that need matching
+/// options only the regex engine supports.
+pub struct RegexMatcher {
+    regex: meta::Regex,
+    patterns: Vec<String>,
+}
+
+impl RegexMatcher {
+    pub fn new(patterns: Vec<String>, options: &MatchOptions) -> Result<Self, SearchError> {
+        let expressions: Vec<String> = patterns
+            .iter()
+            .map(|pattern| {
+                let expression = if options.literal {
+                    regex_syntax::escape(pattern)
+                } else {
+                    pattern.clone()
+                };
+                if options.whole_line {
+                    format!("^(?:{})$", expression)
+                } else if options.whole_word {
+                    // Half boundaries only look outside the match, so patterns that
+                    // start or end with a non-word character still match.
+                    format!(r"\b{{start-half}}(?:{})\b{{end-half}}", expression)
+                } else {
+                    expression
+                }
+            })
+            .collect();
+        let regex = meta::Builder::new()
+            .syntax(syntax::Config::new().case_insensitive(options.case_insensitive))
+            .build_many(&expressions)
+            .map_err(|error| SearchError::InvalidPattern(Box::new(error)))?;
+        Ok(Self { regex, patterns })
+    }
+}
+
+impl Matcher for RegexMatcher {
+    fn find(&self, line: &[u8]) -> Option<Match> {
+        self.regex.find(line).map(|m| Match {
+            start: m.start(),
+            end: m.end(),
+            pattern: m.pattern().as_usize(),
+        })
+    }
+
+    fn find_all(&self, line: &[u8]) -> Vec<Match> {
+        self.regex
+            .find_iter(line)
+            .map(|m| Match {
+                start: m.start(),
+                end: m.end(),
+                pattern: m.pattern().as_usize(),
+            })
+            .collect()
+    }
+
+    fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+}
+
+/// Matches any of a set of literal terms in a single pass over the line.
+pub struct MultiLiteralMatcher {
+    automaton: AhoCorasick,
+    patterns: Vec<String>,
+}
+
+impl MultiLiteralMatcher {
+    pub fn new(patterns: Vec<String>) -> Result<Self, SearchError> {
+        let automaton = AhoCorasick::builder()
+            .match_kind(MatchKind::LeftmostLongest)
+            .build(&patterns)
+            .map_err(SearchError::InvalidPatternSet)?;
+        Ok(Self {
+            automaton,
+            patterns,
+        })
+    }
+}
+
+impl Matcher for MultiLiteralMatcher {
+    fn find(&self, line: &[u8]) -> Option<Match> {
+        self.automaton.find(line).map(|m| Match {
+            start: m.start(),
+            end: m.end(),
+            pattern: m.pattern().as_usize(),
+        })
+    }
+
+    fn find_all(&self, line: &[u8]) -> Vec<Match> {
+        self.automaton
+            .find_iter(line)
+            .map(|m| Match {
+                start: m.start(),
+                end: m.end(),
+                pattern: m.pattern().as_usize(),
+            })
+            .collect()
+    }
+
+    fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+}
+
+/// Returns true if the pattern contains no regex syntax and can be searched
+/// for as a plain literal.
+pub fn is_literal(pattern: &str) -> bool {
+    !pattern.chars().any(regex_syntax::is_meta_character)
+}
+
+/// Returns true if the pattern contains an uppercase letter that it matches
+/// literally. Escapes such as `\W` or `\S` do not count.
+pub fn has_uppercase(pattern: &str, literal: bool) -> bool {
+    if literal {
+        return pattern.chars().any(char::is_uppercase);
+    }
+    match ast::parse::Parser::new().parse(pattern) {
+        Ok(ast) => ast::visit(&ast, UppercaseVisitor(false)).unwrap_or(false),
+        // Let the regex compiler report the syntax error.
+        Err(_) => pattern.chars().any(char::is_uppercase),
+    }
+}
+
+/// Looks for uppercase letters among the literals of a regex syntax tree.
+struct UppercaseVisitor(bool);
+
+impl ast::Visitor for UppercaseVisitor {
+    type Output = bool;
+    type Err = ();
+
+    fn finish(self) -> Result<bool, ()> {
+        Ok(self.0)
+    }
+
+    fn visit_pre(&mut self, ast: &Ast) -> Result<(), ()> {
+        if let Ast::Literal(literal) = ast {
+            self.0 |= literal.c.is_uppercase();
+        }
+        Ok(())
+    }
+
+    fn visit_class_set_item_pre(&mut self, item: &ast::ClassSetItem) -> Result<(), ()> {
+        match item {
+            ast::ClassSetItem::Literal(literal) => self.0 |= literal.c.is_uppercase(),
+            ast::ClassSetItem::Range(range) => {
+                self.0 |= range.start.c.is_uppercase() || range.end.c.is_uppercase();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the start, end and pattern of every match in `line`.
+    fn spans(matcher: &dyn Matcher, line: &str) -> Vec<(usize, usize, usize)> {
+        matcher
+            .find_all(line.as_bytes())
+            .into_iter()
+            .map(|m| (m.start, m.end, m.pattern))
+            .collect()
+    }
+
+    #[test]
+    fn substring_spans_do_not_overlap() {
+        let matcher = SubstringMatcher::new("aa".to_owned());
+        assert_eq!(spans(&matcher, "aaaaa"), [(0, 2, 0), (2, 4, 0)]);
+    }
+
+    #[test]
+    fn regex_spans_name_the_pattern_that_matched() {
+        let patterns = vec![r"\d+".to_owned(), "[a-z]+".to_owned()];
+        let matcher = RegexMatcher::new(patterns, &MatchOptions::default()).unwrap();
+        assert_eq!(
+            spans(&matcher, "ab 12 c"),
+            [(0, 2, 1), (3, 5, 0), (6, 7, 1)]
+        );
+    }
+
+    #[test]
+    fn multi_literal_spans_name_the_pattern_that_matched() {
+        let patterns = vec!["foo".to_owned(), "bar".to_owned(), "foobar".to_owned()];
+        let matcher = MultiLiteralMatcher::new(patterns).unwrap();
+        // The longest of the literals starting at the same place wins.
+        assert_eq!(
+            spans(&matcher, "bar foobar foo"),
+            [(0, 3, 1), (4, 10, 2), (11, 14, 0)]
+        );
+    }
+
+    #[test]
+    fn find_returns_the_first_of_all_matches() {
+        let patterns = vec!["foo".to_owned(), "bar".to_owned()];
+        let matcher = MultiLiteralMatcher::new(patterns).unwrap();
+        let line = b"xx bar foo";
+        assert_eq!(matcher.find(line), matcher.find_all(line).first().copied());
+    }
+
+    #[test]
+    fn literal_patterns_count_any_uppercase_letter() {
+        assert!(has_uppercase("Foo", true));
+        assert!(has_uppercase(r"\W", true));
+        assert!(!has_uppercase("foo.bar", true));
+    }
+
+    #[test]
+    fn regex_escapes_and_classes_do_not_count_as_uppercase() {
+        assert!(!has_uppercase(r"\W+", false));
+        assert!(!has_uppercase(r"foo\S\D\B", false));
+        assert!(!has_uppercase(r"\p{Lu}", false));
+    }
+
+    #[test]
+    fn regex_literals_and_ranges_count_as_uppercase() {
+        assert!(has_uppercase("fooBar", false));
+        assert!(has_uppercase("[A-Z]+", false));
+        assert!(has_uppercase("[xY]", false));
+        assert!(has_uppercase(r"\x41", false));
+    }
+
+    #[test]
+    fn unparsable_regex_falls_back_to_any_uppercase_letter() {
+        assert!(has_uppercase("Foo(", false));
+        assert!(has_uppercase(r"\W(", false));
+        assert!(!has_uppercase("foo(", false));
+    }
+
+    /// Returns true if `pattern`, compiled with `options`, matches `line`.
+    fn matches(pattern: &str, options: MatchOptions, line: &str) -> bool {
+        RegexMatcher::new(vec![pattern.to_owned()], &options)
+            .unwrap()
+            .find(line.as_bytes())
+            .is_some()
+    }
+
+    const WORD: MatchOptions = MatchOptions {
+        literal: false,
+        case_insensitive: false,
+        whole_word: true,
+        whole_line: false,
+    };
+
+    const LINE: MatchOptions = MatchOptions {
+        literal: false,
+        case_insensitive: false,
+        whole_word: false,
+        whole_line: true,
+    };
+
+    #[test]
+    fn whole_words_do_not_match_inside_words() {
+        assert!(matches("id", WORD, "the id is"));
+        assert!(matches("id", WORD, "id"));
+        assert!(!matches("id", WORD, "width"));
+        assert!(!matches("id", WORD, "ids"));
+    }
+
+    #[test]
+    fn whole_words_may_start_or_end_with_non_word_characters() {
+        assert!(matches("-x", WORD, "run -x now"));
+        assert!(matches(r"foo\(\)", WORD, "call foo() now"));
+        assert!(!matches(r"foo\(\)", WORD, "call barfoo() now"));
+        let literal = MatchOptions {
+            literal: true,
+            ..WORD
+        };
+        assert!(matches("(x)", literal, "f (x) g"));
+    }
+
+    #[test]
+    fn whole_lines_wrap_alternations() {
+        assert!(matches("a|abc", LINE, "abc"));
+        assert!(matches("a|abc", LINE, "a"));
+        assert!(!matches("a|abc", LINE, "ab"));
+        assert!(!matches("a|abc", LINE, "xabc"));
+    }
+
+    #[test]
+    fn whole_words_wrap_alternations() {
+        assert!(matches("a|abc", WORD, "x abc y"));
+        assert!(!matches("a|abc", WORD, "abcd"));
+    }
+}
@@ -0,0 +1,203 @@
+use std::fs;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use crossbeam::channel::Receiver;
+
+use crate::job::FilePath;
+use crate::result::SearchResult;
+
+/// The order in which files are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortBy {
+    /// Print files as soon as they are searched
+    None,
+    /// Sort files by path
+    Path,
+    /// Sort files by last modification time
+    Modified,
+    /// Sort files by last access time
+    Accessed,
+    /// Sort files by creation time
+    Created,
+}
+
+impl SortBy {
+    pub const VARIANTS: &'static [&'static str] =
+        &["path", "modified", "accessed", "created", "none"];
+}
+
+impl FromStr for SortBy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "none" => Ok(SortBy::None),
+            "path" => Ok(SortBy::Path),
+            "modified" => Ok(SortBy::Modified),
+            "accessed" => Ok(SortBy::Accessed),
+            "created" => Ok(SortBy::Created),
+            _ => Err(format!("unknown sort order '{}'", value)),
+        }
+    }
+}
+
+/// Prints results as workers send them. Each batch holds the results of a
+/// single file, so a file's output is never interleaved with another's.
+pub struct Printer {
+    receiver: Receiver<Vec<SearchResult>>,
+    context: bool,
+    sort: SortBy,
+    reverse: bool,
+    /// The last line printed
+    previous: Option<SearchResult>,
+}
+
+impl Printer {
+    pub fn new(
+        receiver: Receiver<Vec<SearchResult>>,
+        context: bool,
+        sort: SortBy,
+        reverse: bool,
+    ) -> Self {
+        Self {
+            receiver,
+            context,
+            sort,
+            reverse,
+            previous: None,
+        }
+    }
+
+    /// Prints batches until every sender has been dropped. Unless a sort
+    /// order is set, each batch is printed as soon as it arrives.
+    ///
+    /// If stdout can no longer be written to, printing stops and the receiver
+    /// is dropped, which tells the workers to stop searching.
+    pub fn run(mut self) {
+        let mut out = io::stdout().lock();
+        if let Err(error) = self.print_all(&mut out) {
+            // A closed pipe, as with `| head`, only means no more output is
+            // wanted.
+            if error.kind() != io::ErrorKind::BrokenPipe {
+                eprintln!("Error writing results: {}", error);
+            }
+        }
+    }
+
+    fn print_all<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        if self.sort == SortBy::None {
+            while let Ok(batch) = self.receiver.recv() {
+                self.print_batch(out, batch)?;
+            }
+            return out.flush();
+        }
+
+        let mut batches: Vec<Vec<SearchResult>> = self.receiver.iter().collect();
+        match self.sort {
+            SortBy::None => {}
+            SortBy::Path => batches.sort_by(|a, b| batch_path(a).cmp(&batch_path(b))),
+            SortBy::Modified => sort_by_time(&mut batches, |metadata| metadata.modified()),
+            SortBy::Accessed => sort_by_time(&mut batches, |metadata| metadata.accessed()),
+            SortBy::Created => sort_by_time(&mut batches, |metadata| metadata.created()),
+        }
+        if self.reverse {
+            batches.reverse();
+        }
+        for batch in batches {
+            self.print_batch(out, batch)?;
+        }
+        out.flush()
+    }
+
+    fn print_batch<W: Write>(&mut self, out: &mut W, batch: Vec<SearchResult>) -> io::Result<()> {
+        for result in batch {
+            // With context enabled, groups of lines that are not adjacent
+            // are separated by a `--` line. Whole-file results are not lines,
+            // so they are never separated.
+            if !matches!(result, SearchResult::Line { .. }) {
+                result.display(out)?;
+                continue;
+            }
+            if let Some(previous) = &self.previous {
+                if self.context && !result.follows(previous) {
+                    writeln!(out, "--")?;
+                }
+            }
+            result.display(out)?;
+            self.previous = Some(result);
+        }
+        Ok(())
+    }
+}
+
+/// Returns the path of the file a batch of results belongs to.
+fn batch_path(batch: &[SearchResult]) -> Option<&FilePath> {
+    batch.first().map(SearchResult::path)
+}
+
+/// Sorts batches by one of their file's timestamps, falling back to the path
+/// so that files with equal or unavailable timestamps keep a stable order.
+/// Members of an archive have the archive's timestamps.
+fn sort_by_time<F>(batches: &mut [Vec<SearchResult>], timestamp: F)
+where
+    F: Fn(&fs::Metadata) -> std::io::Result<SystemTime>,
+{
+    batches.sort_by_cached_key(|batch| {
+        let path = batch_path(batch).cloned();
+        let time = path.as_ref().and_then(|path| {
+            fs::metadata(path.disk_path())
+                .and_then(|metadata| timestamp(&metadata))
+                .ok()
+        });
+        (time, path)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam::channel::unbounded;
+    use std::path::PathBuf;
+
+    /// Prints `batches` with context enabled and returns the output.
+    fn print_with_context(batches: Vec<Vec<SearchResult>>) -> String {
+        let mut printer = Printer::new(unbounded().1, true, SortBy::None, false);
+        let mut out = Vec::new();
+        for batch in batches {
+            printer.print_batch(&mut out, batch).unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    fn path(name: &str) -> FilePath {
+        FilePath::Disk(PathBuf::from(name))
+    }
+
+    #[test]
+    fn separators_go_between_groups_of_lines() {
+        let batch = vec![
+            SearchResult::line(path("a"), 0, "x".to_owned(), None),
+            SearchResult::context(path("a"), 1, "y".to_owned()),
+            SearchResult::line(path("a"), 5, "x".to_owned(), None),
+        ];
+        assert_eq!(
+            print_with_context(vec![batch]),
+            "a[0]: x\na[1]- y\n--\na[5]: x\n"
+        );
+    }
+
+    #[test]
+    fn whole_files_are_not_separated() {
+        let batches = vec![
+            vec![SearchResult::file(path("a"))],
+            vec![SearchResult::binary(path("b"))],
+            vec![SearchResult::file(path("c"))],
+        ];
+        assert_eq!(
+            print_with_context(batches),
+            "a\nb: binary file matches\nc\n"
+        );
+    }
+}
@@ -1,26 +1,115 @@
-use std::path::PathBuf;
+use std::io::{self, Write};
 
-pub struct SearchResult {
-    pub path: PathBuf,
-    pub line_number: usize,
-    pub line: String,
+use crate::job::FilePath;
+
+/// Why a line was included in the results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    /// The line matched
+    Match,
+    /// The line surrounds a match
+    Context,
+}
+
+pub enum SearchResult {
+    /// A single line within a file
+    Line {
+        path: FilePath,
+        line_number: usize,
+        line: String,
+        kind: LineKind,
+        /// The pattern that matched, when searching for more than one
+        pattern: Option<String>,
+    },
+    /// A file as a whole, such as one in which nothing matched
+    File { path: FilePath },
+    /// A binary file in which something matched
+    Binary { path: FilePath },
 }
 
 impl SearchResult {
-    pub fn new(path: PathBuf, line_number: usize, line: String) -> Self {
-        Self {
+    pub fn line(path: FilePath, line_number: usize, line: String, pattern: Option<String>) -> Self {
+        SearchResult::Line {
+            path,
+            line_number,
+            line,
+            kind: LineKind::Match,
+            pattern,
+        }
+    }
+
+    pub fn context(path: FilePath, line_number: usize, line: String) -> Self {
+        SearchResult::Line {
             path,
             line_number,
             line,
+            kind: LineKind::Context,
+            pattern: None,
+        }
+    }
+
+    pub fn path(&self) -> &FilePath {
+        match self {
+            SearchResult::Line { path, .. }
+            | SearchResult::File { path }
+            | SearchResult::Binary { path } => path,
+        }
+    }
+
+    /// Returns true if this result is the line directly after `previous` in
+    /// the same file.
+    pub fn follows(&self, previous: &SearchResult) -> bool {
+        match (self, previous) {
+            (
+                SearchResult::Line {
+                    path, line_number, ..
+                },
+                SearchResult::Line {
+                    path: previous_path,
+                    line_number: previous_line_number,
+                    ..
+                },
+            ) => path == previous_path && *line_number == previous_line_number + 1,
+            _ => false,
         }
     }
 
-    pub fn display(&self) {
-        println!(
-            "{}[{}]: {}",
-            self.path.display(),
-            self.line_number,
-            self.line
-        );
+    pub fn file(path: FilePath) -> Self {
+        SearchResult::File { path }
+    }
+
+    pub fn binary(path: FilePath) -> Self {
+        SearchResult::Binary { path }
+    }
+
+    /// Writes the result to `out` as a single line.
+    pub fn display<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        match self {
+            SearchResult::Line {
+                path,
+                line_number,
+                line,
+                pattern: Some(pattern),
+                ..
+            } => writeln!(out, "{}[{}] ({}): {}", path, line_number, pattern, line),
+            SearchResult::Line {
+                path,
+                line_number,
+                line,
+                kind: LineKind::Match,
+                ..
+            } => writeln!(out, "{}[{}]: {}", path, line_number, line),
+            SearchResult::Line {
+                path,
+                line_number,
+                line,
+                kind: LineKind::Context,
+                ..
+            } => writeln!(out, "{}[{}]- {}", path, line_number, line),
+            SearchResult::File { path } => writeln!(out, "{}", path),
+            SearchResult::Binary { path } => {
+                writeln!(out, "{}: binary file matches", path)
+            }
+        }
     }
 }
@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::error::SearchError;
+
+/// The built-in file types and the file name globs that define them.
+const DEFAULT_TYPES: &[(&str, &[&str])] = &[
+    ("c", &["*.[chH]", "*.[chH].in", "*.cats"]),
+    (
+        "cpp",
+        &[
+            "*.[ChH]",
+            "*.cc",
+            "*.[ch]pp",
+            "*.[ch]xx",
+            "*.hh",
+            "*.inl",
+            "*.[ChH].in",
+        ],
+    ),
+    ("cs", &["*.cs"]),
+    ("css", &["*.css", "*.scss", "*.sass", "*.less"]),
+    ("go", &["*.go"]),
+    ("html", &["*.htm", "*.html", "*.xhtml"]),
+    ("java", &["*.java", "*.jsp"]),
+    ("js", &["*.js", "*.jsx", "*.cjs", "*.mjs", "*.vue"]),
+    ("json", &["*.json", "*.jsonl"]),
+    ("kotlin", &["*.kt", "*.kts"]),
+    ("make", &["[Mm]akefile", "GNUmakefile", "*.mk", "*.mak"]),
+    (
+        "md",
+        &["*.markdown", "*.md", "*.mdown", "*.mdwn", "*.mkd", "*.mkdn"],
+    ),
+    ("php", &["*.php", "*.php3", "*.php4", "*.php5", "*.phtml"]),
+    ("py", &["*.py", "*.pyi"]),
+    ("rb", &["*.rb", "*.gemspec", "Gemfile", "Rakefile"]),
+    ("rust", &["*.rs"]),
+    (
+        "sh",
+        &["*.sh", "*.bash", "*.zsh", ".bashrc", ".zshrc", ".profile"],
+    ),
+    ("sql", &["*.sql"]),
+    ("swift", &["*.swift"]),
+    ("toml", &["*.toml", "Cargo.lock"]),
+    ("ts", &["*.ts", "*.tsx", "*.cts", "*.mts"]),
+    ("txt", &["*.txt"]),
+    ("xml", &["*.xml", "*.xsd", "*.xsl", "*.xslt", "*.svg"]),
+    ("yaml", &["*.yaml", "*.yml"]),
+];
+
+/// Maps file type names such as `rust` or `py` to the globs that select them.
+pub struct TypeRegistry {
+    types: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for TypeRegistry {
+    fn default() -> Self {
+        let types = DEFAULT_TYPES
+            .iter()
+            .map(|(name, globs)| {
+                let globs = globs.iter().map(|glob| glob.to_string()).collect();
+                (name.to_string(), globs)
+            })
+            .collect();
+        Self { types }
+    }
+}
+
+impl TypeRegistry {
+    /// Adds a glob to a type from a `name:glob` definition, creating the type
+    /// if it does not exist yet.
+    pub fn add(&mut self, definition: &str) -> Result<(), SearchError> {
+        let (name, glob) = definition
+            .split_once(':')
+            .filter(|(name, glob)| !name.is_empty() && !glob.is_empty())
+            .ok_or_else(|| SearchError::InvalidTypeDefinition(definition.to_string()))?;
+        self.types
+            .entry(name.to_string())
+            .or_default()
+            .push(glob.to_string());
+        Ok(())
+    }
+
+    /// Prints every type and its globs, one type per line.
+    pub fn print(&self) {
+        for (name, globs) in &self.types {
+            println!("{}: {}", name, globs.join(", "));
+        }
+    }
+
+    /// Compiles the selected and excluded types into a filter.
+    pub fn filter(
+        &self,
+        selected: &[String],
+        excluded: &[String],
+    ) -> Result<TypeFilter, SearchError> {
+        Ok(TypeFilter {
+            selected: self.glob_set(selected)?,
+            excluded: self.glob_set(excluded)?,
+            has_selected: !selected.is_empty(),
+        })
+    }
+
+    fn glob_set(&self, names: &[String]) -> Result<GlobSet, SearchError> {
+        let mut builder = GlobSetBuilder::new();
+        for name in names {
+            let globs = self
+                .types
+                .get(name)
+                .ok_or_else(|| SearchError::UnknownFileType(name.clone()))?;
+            for glob in globs {
+                builder.add(Glob::new(glob).map_err(SearchError::InvalidGlob)?);
+            }
+        }
+        builder.build().map_err(SearchError::InvalidGlob)
+    }
+}
+
+/// Selects files by type, matching the type globs against file names.
+pub struct TypeFilter {
+    selected: GlobSet,
+    excluded: GlobSet,
+    has_selected: bool,
+}
+
+impl TypeFilter {
+    /// Returns true if the file is of a selected type, or no type was
+    /// selected, and it is not of an excluded type.
+    pub fn is_included(&self, path: &Path) -> bool {
+        let name = match path.file_name() {
+            Some(name) => Path::new(name),
+            None => return false,
+        };
+        if self.excluded.is_match(name) {
+            return false;
+        }
+        !self.has_selected || self.selected.is_match(name)
+    }
+}
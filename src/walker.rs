@@ -0,0 +1,404 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use crossbeam::deque::{Injector, Stealer, Worker as Deque};
+use crossbeam::utils::Backoff;
+
+use crate::archive::{self, ArchiveKind};
+use crate::error::SearchError;
+use crate::globs::GlobFilter;
+use crate::ignore_rules::IgnoreRules;
+use crate::job::Job;
+use crate::types::TypeFilter;
+use crate::worklist::Worklist;
+
+/// Options that change which entries directory discovery skips.
+#[derive(Default)]
+pub struct DiscoveryOptions {
+    /// The directory the search starts from
+    pub root: PathBuf,
+    /// Don't respect ignore files
+    pub no_ignore: bool,
+    /// Include hidden files and directories
+    pub hidden: bool,
+    /// Globs that select which paths are searched, if any were given
+    pub globs: Option<GlobFilter>,
+    /// File types that select which files are searched, if any were given
+    pub types: Option<TypeFilter>,
+    /// How many directories deep to descend below the root, if limited
+    pub max_depth: Option<usize>,
+    /// Don't descend into directories on other file systems than the root
+    pub one_file_system: bool,
+    /// Follow symbolic links instead of skipping them
+    pub follow_links: bool,
+    /// Search the members of tar and zip archives instead of the archives
+    pub archives: bool,
+}
+
+/// Identifies a file by the device it lives on and its inode number.
+type FileId = (u64, u64);
+
+/// Returns the device and inode numbers of a file.
+#[cfg(unix)]
+fn file_id(metadata: &fs::Metadata) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+/// Returns the device and inode numbers of a file, which are not available
+/// on this platform.
+#[cfg(not(unix))]
+fn file_id(_metadata: &fs::Metadata) -> Option<FileId> {
+    None
+}
+
+/// Returns true if a file or directory is hidden, i.e. its name starts with a
+/// dot.
+fn is_hidden(name: &OsStr) -> bool {
+    name.to_string_lossy().starts_with('.')
+}
+
+/// A directory waiting to be read.
+struct Dir {
+    path: PathBuf,
+    /// The absolute form of `path`, which ignore rules are matched against
+    abs_path: PathBuf,
+    /// How far below the root the directory is
+    depth: usize,
+    /// The ignore rules inherited from the parent directory, or `None` when
+    /// ignore files are not honoured
+    rules: Option<Arc<IgnoreRules>>,
+    /// The directory itself and every directory above it, when following
+    /// symbolic links, to detect loops
+    ancestors: Vec<FileId>,
+}
+
+/// Discovers files to search with a fixed number of threads. Directories
+/// waiting to be read sit in per-thread queues, and idle threads steal from
+/// the others, so discovery keeps every thread busy on wide and deep trees.
+pub struct Walker {
+    options: DiscoveryOptions,
+    worklist: Arc<Worklist>,
+    num_threads: usize,
+    /// The device the root lives on, when staying on one file system
+    root_device: Option<u64>,
+}
+
+impl Walker {
+    pub fn new(options: DiscoveryOptions, worklist: Arc<Worklist>, num_threads: usize) -> Self {
+        Self {
+            options,
+            worklist,
+            num_threads: num_threads.max(1),
+            root_device: None,
+        }
+    }
+
+    /// Adds a job to the worklist for every file below the root that is not
+    /// skipped, returning once the whole tree has been walked. Only failing
+    /// to read the root is an error; other unreadable directories are
+    /// reported and skipped.
+    pub fn run(mut self) -> Result<(), SearchError> {
+        let root = self.options.root.clone();
+        let root_id = fs::metadata(&root)
+            .ok()
+            .and_then(|metadata| file_id(&metadata));
+        if self.options.one_file_system {
+            self.root_device = root_id.map(|(device, _)| device);
+        }
+        let abs_path = fs::canonicalize(&root).unwrap_or_else(|_| root.clone());
+        let rules = (!self.options.no_ignore).then(|| IgnoreRules::for_root(&abs_path));
+        let root_dir = Dir {
+            path: root.clone(),
+            abs_path,
+            depth: 0,
+            rules,
+            ancestors: root_id
+                .into_iter()
+                .filter(|_| self.options.follow_links)
+                .collect(),
+        };
+
+        let entries =
+            fs::read_dir(&root).map_err(|_| SearchError::InvalidDir(root.display().to_string()))?;
+        let injector = Injector::new();
+        let pending = AtomicUsize::new(0);
+        self.read_dir(&root_dir, entries, |dir| {
+            pending.fetch_add(1, Ordering::SeqCst);
+            injector.push(dir);
+        });
+
+        let queues: Vec<Deque<Dir>> = (0..self.num_threads).map(|_| Deque::new_lifo()).collect();
+        let stealers: Vec<Stealer<Dir>> = queues.iter().map(Deque::stealer).collect();
+        thread::scope(|scope| {
+            for queue in queues {
+                let (walker, injector, stealers, pending) = (&self, &injector, &stealers, &pending);
+                scope.spawn(move || walker.work(queue, injector, stealers, pending));
+            }
+        });
+        Ok(())
+    }
+
+    /// Reads directories until every queue is empty and no thread is still
+    /// reading a directory that could add more, or until the worklist is
+    /// abandoned.
+    fn work(
+        &self,
+        queue: Deque<Dir>,
+        injector: &Injector<Dir>,
+        stealers: &[Stealer<Dir>],
+        pending: &AtomicUsize,
+    ) {
+        let backoff = Backoff::new();
+        while !self.worklist.is_abandoned() {
+            match find_dir(&queue, injector, stealers) {
+                Some(dir) => {
+                    backoff.reset();
+                    match fs::read_dir(&dir.path) {
+                        Ok(entries) => self.read_dir(&dir, entries, |child| {
+                            pending.fetch_add(1, Ordering::SeqCst);
+                            queue.push(child);
+                        }),
+                        Err(_) => {
+                            eprintln!(
+                                "{}",
+                                SearchError::InvalidDir(dir.path.display().to_string())
+                            )
+                        }
+                    }
+                    pending.fetch_sub(1, Ordering::SeqCst);
+                }
+                None if pending.load(Ordering::SeqCst) == 0 => break,
+                None => backoff.snooze(),
+            }
+        }
+    }
+
+    /// Adds a job for every file in `dir` that is not skipped and hands every
+    /// subdirectory that is not skipped to `push`.
+    fn read_dir<F>(&self, dir: &Dir, entries: fs::ReadDir, mut push: F)
+    where
+        F: FnMut(Dir),
+    {
+        if self
+            .options
+            .max_depth
+            .is_some_and(|max_depth| dir.depth >= max_depth)
+        {
+            return;
+        }
+        let rules = dir.rules.as_ref().map(|rules| rules.for_dir(&dir.abs_path));
+
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(error) => {
+                    eprintln!("{}", SearchError::IoError(error));
+                    continue;
+                }
+            };
+            // Skipping hidden entries here means hidden directories are never
+            // read at all.
+            if !self.options.hidden && is_hidden(&entry.file_name()) {
+                continue;
+            }
+            let path = entry.path();
+            let abs_path = dir.abs_path.join(entry.file_name());
+            let is_dir = match entry.file_type() {
+                Ok(file_type) if file_type.is_symlink() => {
+                    if !self.options.follow_links {
+                        continue;
+                    }
+                    match fs::metadata(&path) {
+                        Ok(metadata) => metadata.is_dir(),
+                        // Broken links have nothing to search.
+                        Err(_) => continue,
+                    }
+                }
+                Ok(file_type) => file_type.is_dir(),
+                Err(_) => continue,
+            };
+            if let Some(rules) = &rules {
+                // Git's own metadata is never worth searching.
+                if (is_dir && entry.file_name() == ".git") || rules.is_ignored(&abs_path, is_dir) {
+                    continue;
+                }
+            }
+            let archive_kind =
+                ArchiveKind::from_path(&path).filter(|_| !is_dir && self.options.archives);
+            // An archive that is searched stands in for a directory, so only
+            // excludes apply to it; its members are filtered one by one.
+            let filter_as_dir = is_dir || archive_kind.is_some();
+            if let Some(globs) = &self.options.globs {
+                let relative_path = path.strip_prefix(&self.options.root).unwrap_or(&path);
+                if !globs.is_included(relative_path, filter_as_dir) {
+                    continue;
+                }
+            }
+            if let Some(types) = &self.options.types {
+                if !filter_as_dir && !types.is_included(&path) {
+                    continue;
+                }
+            }
+            if !is_dir {
+                let added = match archive_kind {
+                    Some(kind) => self.add_archive(&path, kind),
+                    None => self.worklist.add(Job::File(path)),
+                };
+                match added {
+                    Ok(()) => {}
+                    // The workers abandoned the search, so stop adding jobs.
+                    Err(SearchError::WorklistClosed) => return,
+                    // An unreadable archive is skipped like an unreadable
+                    // directory.
+                    Err(error) => eprintln!("{}", error),
+                }
+                continue;
+            }
+
+            let mut ancestors = Vec::new();
+            if self.root_device.is_some() || self.options.follow_links {
+                let id = match fs::metadata(&path) {
+                    Ok(metadata) => file_id(&metadata),
+                    Err(_) => continue,
+                };
+                if let (Some(root_device), Some((device, _))) = (self.root_device, id) {
+                    if device != root_device {
+                        continue;
+                    }
+                }
+                if let (true, Some(id)) = (self.options.follow_links, id) {
+                    if dir.ancestors.contains(&id) {
+                        eprintln!("{}", SearchError::SymlinkLoop(path.display().to_string()));
+                        continue;
+                    }
+                    ancestors = dir.ancestors.clone();
+                    ancestors.push(id);
+                }
+            }
+            push(Dir {
+                path,
+                abs_path,
+                depth: dir.depth + 1,
+                rules: rules.clone(),
+                ancestors,
+            });
+        }
+    }
+
+    /// Adds a job for the members of the archive at `path` that are not
+    /// skipped.
+    fn add_archive(&self, path: &Path, kind: ArchiveKind) -> Result<(), SearchError> {
+        let members =
+            archive::list_members(path, kind, |name| self.is_member_included(path, name))?;
+        if members.is_empty() {
+            return Ok(());
+        }
+        self.worklist.add(Job::Members {
+            archive: path.to_path_buf(),
+            kind,
+            members,
+        })
+    }
+
+    /// Returns true if the member `name` of the archive at `archive` passes
+    /// the same filters as a file at `archive/name` would, with the
+    /// directories inside the archive filtered like real ones.
+    fn is_member_included(&self, archive: &Path, name: &Path) -> bool {
+        if !self.options.hidden && name.iter().any(is_hidden) {
+            return false;
+        }
+        if let Some(globs) = &self.options.globs {
+            let archive = archive.strip_prefix(&self.options.root).unwrap_or(archive);
+            let dirs_included = name
+                .ancestors()
+                .skip(1)
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .all(|dir| globs.is_included(&archive.join(dir), true));
+            if !dirs_included || !globs.is_included(&archive.join(name), false) {
+                return false;
+            }
+        }
+        if let Some(types) = &self.options.types {
+            if !types.is_included(name) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Takes the next directory to read: from this thread's own queue first,
+/// then from the shared injector, then from another thread's queue.
+fn find_dir(
+    queue: &Deque<Dir>,
+    injector: &Injector<Dir>,
+    stealers: &[Stealer<Dir>],
+) -> Option<Dir> {
+    queue.pop().or_else(|| {
+        iter::repeat_with(|| {
+            injector
+                .steal_batch_and_pop(queue)
+                .or_else(|| stealers.iter().map(Stealer::steal).collect())
+        })
+        .find(|steal| !steal.is_retry())
+        .and_then(|steal| steal.success())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::job::FilePath;
+    use crate::worker::tests::search_tree;
+    use crossbeam::channel::unbounded;
+
+    /// Members of an archive are filtered like files in a directory, and the
+    /// archive itself is only subject to excludes.
+    #[test]
+    fn archive_members_are_filtered_like_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("files.tar");
+        let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+        for name in ["src/a.txt", "src/b.rs", ".git/c.txt", "vendor/d.txt"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(7);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, "needle\n".as_bytes())
+                .unwrap();
+        }
+        builder.finish().unwrap();
+        drop(builder);
+
+        let globs = ["*.txt".to_owned(), "!**/vendor".to_owned()];
+        let options = DiscoveryOptions {
+            root: dir.path().to_path_buf(),
+            no_ignore: true,
+            globs: Some(GlobFilter::new(&globs).unwrap()),
+            archives: true,
+            ..Default::default()
+        };
+        let (result_sender, result_receiver) = unbounded();
+        search_tree(options, result_sender);
+
+        let paths: Vec<FilePath> = result_receiver
+            .iter()
+            .flatten()
+            .map(|result| result.path().clone())
+            .collect();
+        assert_eq!(
+            paths,
+            [FilePath::Member {
+                archive,
+                name: "src/a.txt".into()
+            }]
+        );
+    }
+}
@@ -1,81 +1,458 @@
 use crossbeam::channel::Sender;
-use tokio::fs;
-use tokio::io::{AsyncBufReadExt, BufReader};
+use encoding_rs::Encoding;
+use encoding_rs_io::DecodeReaderBytesBuilder;
 
+use crate::archive;
+use crate::decompress::Compression;
 use crate::error::SearchError;
+use crate::job::{FilePath, Job};
+use crate::matcher::Matcher;
 use crate::result::SearchResult;
 use crate::worklist::Worklist;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+use std::ops::ControlFlow;
 use std::path::Path;
 use std::sync::Arc;
 
+/// Number of bytes at the start of a file that are checked for NUL bytes to
+/// decide whether it is binary.
+const BINARY_DETECTION_BYTES: usize = 8192;
+
+/// How files that look binary are handled.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryMode {
+    /// Don't search binary files
+    #[default]
+    Skip,
+    /// Search binary files but only report whether they match
+    Report,
+    /// Search binary files as if they were text
+    Text,
+}
+
+/// Options that change which lines and files a worker reports.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SearchOptions {
+    /// Report lines that do not match instead of lines that do
+    pub invert_match: bool,
+    /// Report only the files in which nothing matched
+    pub files_without_match: bool,
+    /// Number of lines to report before each match
+    pub before_context: usize,
+    /// Number of lines to report after each match
+    pub after_context: usize,
+    /// How files that look binary are handled
+    pub binary: BinaryMode,
+    /// The encoding to transcode files from, unless they start with a BOM;
+    /// files without a BOM are searched as they are when this is `None`
+    pub encoding: Option<&'static Encoding>,
+    /// Search the decompressed contents of compressed files
+    pub search_zip: bool,
+}
+
+/// Decodes a line for display, replacing invalid UTF-8 with replacement
+/// characters.
+fn decode(line: &[u8]) -> String {
+    String::from_utf8_lossy(line).into_owned()
+}
+
 pub struct Worker {
-    search_term: String,
+    matcher: Arc<dyn Matcher>,
+    options: SearchOptions,
     worklist: Arc<Worklist>,
     result_sender: Sender<Vec<SearchResult>>,
 }
 
 impl Worker {
     pub fn new(
-        search_term: String,
+        matcher: Arc<dyn Matcher>,
+        options: SearchOptions,
         worklist: Arc<Worklist>,
         result_sender: Sender<Vec<SearchResult>>,
     ) -> Self {
         Self {
-            search_term,
+            matcher,
+            options,
             worklist,
             result_sender,
         }
     }
 
-    async fn find_in_file<P>(&self, path: P) -> Result<Vec<SearchResult>, SearchError>
-    where
-        P: AsRef<Path>,
-    {
-        let path = path.as_ref().to_owned();
+    /// Searches a file on disk.
+    fn find_in_file(&self, path: &Path) -> Result<Vec<SearchResult>, SearchError> {
         if !path.exists() {
             return Ok(Vec::new());
         }
+        let file = fs::File::open(path)?;
+        self.search(FilePath::Disk(path.to_path_buf()), Box::new(file))
+    }
 
-        let file = fs::File::open(&path).await?;
-        let reader = BufReader::with_capacity(8192, file);
-        let mut lines = reader.lines();
+    /// Searches `source`, the contents of the file at `path`.
+    fn search(
+        &self,
+        path: FilePath,
+        source: Box<dyn Read + '_>,
+    ) -> Result<Vec<SearchResult>, SearchError> {
+        let mut source = BufReader::new(source);
+        let compression = if self.options.search_zip {
+            Compression::detect(source.fill_buf()?, path.inner_path())
+        } else {
+            None
+        };
+        let contents: Box<dyn Read + '_> = match compression {
+            Some(compression) => compression.decoder(source)?,
+            None => Box::new(source),
+        };
+        // Transcoding to UTF-8 keeps every newline, so line numbers still
+        // refer to the original file. Binary detection looks at the
+        // transcoded text, as UTF-16 is full of NUL bytes.
+        let mut decoder = DecodeReaderBytesBuilder::new()
+            .encoding(self.options.encoding)
+            .bom_override(true)
+            .strip_bom(true)
+            .build(contents);
+        // Decoders may return less than a block per read, so the first block
+        // is read in full before looking for NUL bytes.
+        let mut head = Vec::with_capacity(BINARY_DETECTION_BYTES);
+        (&mut decoder)
+            .take(BINARY_DETECTION_BYTES as u64)
+            .read_to_end(&mut head)?;
+        let is_binary = self.options.binary != BinaryMode::Text && head.contains(&0);
+        if is_binary && self.options.binary == BinaryMode::Skip {
+            return Ok(Vec::new());
+        }
+        let reader = BufReader::new(head.as_slice().chain(decoder));
         let mut matching_lines = Vec::new();
 
-        let mut line_number = 0;
-        while let Some(line) = lines.next_line().await? {
-            if line.contains(&self.search_term) {
-                matching_lines.push(SearchResult::new(path.clone(), line_number, line));
-            }
+        // Lines kept around in case a later line matches and they become its
+        // before context, and how many lines after the last match are still due.
+        // The buffer grows as lines arrive rather than up front, as the
+        // number of lines comes straight from the user.
+        let mut before: VecDeque<(usize, Vec<u8>)> = VecDeque::new();
+        let mut after_remaining = 0;
 
-            line_number += 1;
+        for (line_number, line) in reader.split(b'\n').enumerate() {
+            let mut line = line?;
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            let found = self.matcher.find(&line);
+            if found.is_some() != self.options.invert_match {
+                if self.options.files_without_match {
+                    // One match is enough to rule the file out.
+                    return Ok(Vec::new());
+                }
+                if is_binary {
+                    // Lines of a binary file are not worth printing.
+                    return Ok(vec![SearchResult::binary(path)]);
+                }
+                for (context_number, context_line) in before.drain(..) {
+                    matching_lines.push(SearchResult::context(
+                        path.clone(),
+                        context_number,
+                        decode(&context_line),
+                    ));
+                }
+                let patterns = self.matcher.patterns();
+                let pattern = found
+                    .filter(|_| patterns.len() > 1)
+                    .map(|found| patterns[found.pattern].clone());
+                matching_lines.push(SearchResult::line(
+                    path.clone(),
+                    line_number,
+                    decode(&line),
+                    pattern,
+                ));
+                after_remaining = self.options.after_context;
+            } else if after_remaining > 0 {
+                matching_lines.push(SearchResult::context(
+                    path.clone(),
+                    line_number,
+                    decode(&line),
+                ));
+                after_remaining -= 1;
+            } else if self.options.before_context > 0 {
+                if before.len() == self.options.before_context {
+                    before.pop_front();
+                }
+                before.push_back((line_number, line));
+            }
         }
 
+        if self.options.files_without_match {
+            return Ok(vec![SearchResult::file(path)]);
+        }
         Ok(matching_lines)
     }
 
-    pub async fn process_jobs(&self) {
-        loop {
-            let job = self.worklist.next();
-            if let Some(job) = job {
-                let path = job.as_path();
-                match self.find_in_file(path).await {
-                    Ok(results) => {
-                        if let Err(send_error) = self.result_sender.send(results) {
-                            eprintln!("Error sending results: {}", send_error);
-                            break;
-                        }
+    /// Sends the results of searching the file at `path`, or reports why it
+    /// could not be searched. Returns false once results can no longer be
+    /// sent.
+    fn report(&self, path: &FilePath, results: Result<Vec<SearchResult>, SearchError>) -> bool {
+        match results {
+            Ok(results) if results.is_empty() => true,
+            Ok(results) => self.result_sender.send(results).is_ok(),
+            Err(_) => {
+                if let Some(file_name) = path.inner_path().file_name() {
+                    if let Some(name) = file_name.to_str() {
+                        eprintln!("Error Processing File {}", name);
                     }
-                    Err(_) => {
-                        if let Some(file_name) = path.file_name() {
-                            if let Some(name) = file_name.to_str() {
-                                eprintln!("Error Processing File {}", name);
-                            }
+                }
+                true
+            }
+        }
+    }
+
+    /// Searches files from the worklist until it is closed and drained. The
+    /// file I/O blocks, so this must run on its own thread.
+    pub fn process_jobs(&self) {
+        while let Some(job) = self.worklist.next() {
+            let sent = match job {
+                Job::File(path) => {
+                    let results = self.find_in_file(&path);
+                    self.report(&FilePath::Disk(path), results)
+                }
+                Job::Members {
+                    archive,
+                    kind,
+                    members,
+                } => {
+                    let mut sent = true;
+                    let read = archive::read_members(&archive, kind, &members, |name, contents| {
+                        let path = FilePath::Member {
+                            archive: archive.clone(),
+                            name: name.to_path_buf(),
+                        };
+                        let results = self.search(path.clone(), Box::new(contents));
+                        sent = self.report(&path, results);
+                        if sent {
+                            ControlFlow::Continue(())
+                        } else {
+                            ControlFlow::Break(())
                         }
+                    });
+                    if let Err(error) = read {
+                        eprintln!("{}", error);
                     }
+                    sent
                 }
-            } else {
+            };
+            if !sent {
+                // The printer has stopped, so nothing more can be reported.
+                self.worklist.abandon();
                 break;
             }
         }
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::matcher::SubstringMatcher;
+    use crate::result::LineKind;
+    use crate::walker::{DiscoveryOptions, Walker};
+    use crossbeam::channel::{bounded, unbounded};
+    use std::thread;
+    use std::time::Duration;
+
+    use LineKind::{Context, Match};
+
+    /// Searches `lines` for `match` and returns the number and kind of every
+    /// line reported.
+    fn search(lines: &[&str], options: SearchOptions) -> Vec<(usize, LineKind)> {
+        search_bytes(lines.join("\n").as_bytes(), options)
+    }
+
+    /// Searches a file holding `contents` for `match` and returns the number
+    /// and kind of every line reported.
+    fn search_bytes(contents: &[u8], options: SearchOptions) -> Vec<(usize, LineKind)> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, contents).unwrap();
+        let worker = Worker::new(
+            Arc::new(SubstringMatcher::new("match".to_owned())),
+            options,
+            Arc::new(Worklist::new(1)),
+            unbounded().0,
+        );
+        worker
+            .find_in_file(&path)
+            .unwrap()
+            .into_iter()
+            .map(|result| match result {
+                SearchResult::Line {
+                    line_number, kind, ..
+                } => (line_number, kind),
+                _ => panic!("expected a line"),
+            })
+            .collect()
+    }
+
+    /// Returns options that report the given number of context lines.
+    fn context(before_context: usize, after_context: usize) -> SearchOptions {
+        SearchOptions {
+            before_context,
+            after_context,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn overlapping_context_windows_are_merged() {
+        let lines = ["a", "match", "b", "match", "c", "d"];
+        assert_eq!(
+            search(&lines, context(2, 2)),
+            [
+                (0, Context),
+                (1, Match),
+                (2, Context),
+                (3, Match),
+                (4, Context),
+                (5, Context)
+            ]
+        );
+    }
+
+    #[test]
+    fn adjacent_context_windows_are_not_repeated() {
+        let lines = ["a", "match", "b", "c", "match", "d"];
+        assert_eq!(
+            search(&lines, context(1, 1)),
+            [
+                (0, Context),
+                (1, Match),
+                (2, Context),
+                (3, Context),
+                (4, Match),
+                (5, Context)
+            ]
+        );
+    }
+
+    #[test]
+    fn context_windows_stop_at_the_start_and_end_of_the_file() {
+        let lines = ["match", "a", "b", "c", "d", "e", "match"];
+        assert_eq!(
+            search(&lines, context(2, 2)),
+            [
+                (0, Match),
+                (1, Context),
+                (2, Context),
+                (4, Context),
+                (5, Context),
+                (6, Match)
+            ]
+        );
+    }
+
+    #[test]
+    fn inverted_matches_get_context_from_matching_lines() {
+        let lines = ["match", "match", "a", "match", "match"];
+        let options = SearchOptions {
+            invert_match: true,
+            ..context(1, 1)
+        };
+        assert_eq!(
+            search(&lines, options),
+            [(1, Context), (2, Match), (3, Context)]
+        );
+    }
+
+    #[test]
+    fn huge_context_is_not_allocated_up_front() {
+        let lines = ["a", "b", "match", "c"];
+        assert_eq!(
+            search(&lines, context(100_000_000_000, 100_000_000_000)),
+            [(0, Context), (1, Context), (2, Match), (3, Context)]
+        );
+    }
+
+    #[test]
+    fn utf16_lines_are_numbered_as_in_the_original_file() {
+        let mut contents = vec![0xFF, 0xFE];
+        for unit in "a\r\nmatch\r\nb\r\nc match\r\n".encode_utf16() {
+            contents.extend_from_slice(&unit.to_le_bytes());
+        }
+        assert_eq!(
+            search_bytes(&contents, SearchOptions::default()),
+            [(1, Match), (3, Match)]
+        );
+    }
+
+    /// Searches the files `options` discovers for `needle`, with a single
+    /// worker and a single discovery thread as on a one-core machine, and
+    /// sends each file's results to `results`. Returns once the search ends.
+    pub(crate) fn search_tree(options: DiscoveryOptions, results: Sender<Vec<SearchResult>>) {
+        let worklist = Arc::new(Worklist::new(1));
+        let matcher = Arc::new(SubstringMatcher::new("needle".to_owned()));
+        let worker = Worker::new(
+            matcher,
+            SearchOptions::default(),
+            Arc::clone(&worklist),
+            results,
+        );
+        let worker = thread::spawn(move || worker.process_jobs());
+        Walker::new(options, Arc::clone(&worklist), 1)
+            .run()
+            .unwrap();
+        worklist.close();
+        worker.join().unwrap();
+    }
+
+    /// Returns options that discover every file in a directory holding 100
+    /// files that contain `needle`, which is more than the worklist holds.
+    fn needles(dir: &Path) -> DiscoveryOptions {
+        for i in 0..100 {
+            fs::write(dir.join(format!("{}.txt", i)), "needle\n").unwrap();
+        }
+        DiscoveryOptions {
+            root: dir.to_path_buf(),
+            no_ignore: true,
+            ..Default::default()
+        }
+    }
+
+    /// A single worker must finish a search with more files than the
+    /// worklist holds.
+    #[test]
+    fn single_worker_does_not_hang() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = needles(dir.path());
+
+        let (done_sender, done_receiver) = unbounded();
+        thread::spawn(move || {
+            let (result_sender, result_receiver) = unbounded();
+            search_tree(options, result_sender);
+            done_sender.send(result_receiver.iter().count()).unwrap();
+        });
+
+        let batches = done_receiver
+            .recv_timeout(Duration::from_secs(30))
+            .expect("search did not finish");
+        assert_eq!(batches, 100);
+    }
+
+    /// Once results can no longer be sent, as when stdout is closed, workers
+    /// and discovery must stop instead of blocking on a full worklist.
+    #[test]
+    fn dropped_results_stop_the_search() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = needles(dir.path());
+
+        let (result_sender, result_receiver) = bounded(1);
+        let (done_sender, done_receiver) = unbounded();
+        thread::spawn(move || {
+            search_tree(options, result_sender);
+            done_sender.send(()).unwrap();
+        });
+
+        result_receiver.recv().unwrap();
+        drop(result_receiver);
+        done_receiver
+            .recv_timeout(Duration::from_secs(30))
+            .expect("search did not stop");
+    }
+}
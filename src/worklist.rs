@@ -1,30 +1,102 @@
-use crossbeam::channel::{unbounded, Receiver, Sender};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError, RwLock};
 
+use crossbeam::channel::{bounded, select, Receiver, Sender, TryRecvError};
+
+use crate::error::SearchError;
 use crate::job::Job;
 
 pub struct Worklist {
-    sender: Sender<Option<Job>>,
-    receiver: Receiver<Option<Job>>,
+    /// Taken when the worklist is closed, which disconnects the channel
+    sender: RwLock<Option<Sender<Job>>>,
+    receiver: Receiver<Job>,
+    /// Disconnected when the worklist is abandoned, which wakes every `add`
+    /// and `next` blocked on the channel
+    abandoned: Receiver<()>,
+    /// Dropped when the worklist is abandoned; nothing is ever sent on it
+    abandon_sender: Mutex<Option<Sender<()>>>,
+    capacity: usize,
+    /// The most jobs that have been waiting in the worklist at once
+    peak_depth: AtomicUsize,
 }
 
 impl Worklist {
-    pub fn new() -> Self {
-        let (sender, receiver) = unbounded();
-        Self { sender, receiver }
+    /// Creates a worklist that holds at most `capacity` jobs, so that adding
+    /// a job blocks while workers lag behind discovery.
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        let (sender, receiver) = bounded(capacity);
+        let (abandon_sender, abandoned) = bounded(0);
+        Self {
+            sender: RwLock::new(Some(sender)),
+            receiver,
+            abandoned,
+            abandon_sender: Mutex::new(Some(abandon_sender)),
+            capacity,
+            peak_depth: AtomicUsize::new(0),
+        }
     }
 
-    pub fn add(&self, job: Job) {
-        self.sender.send(Some(job)).unwrap();
+    /// Adds a job, blocking while the worklist is full. Fails once the
+    /// worklist has been closed or abandoned.
+    pub fn add(&self, job: Job) -> Result<(), SearchError> {
+        if self.is_abandoned() {
+            return Err(SearchError::WorklistClosed);
+        }
+        let sender = self.sender.read().unwrap_or_else(PoisonError::into_inner);
+        let sender = sender.as_ref().ok_or(SearchError::WorklistClosed)?;
+        select! {
+            send(sender, job) -> result => result.map_err(|_| SearchError::WorklistClosed)?,
+            recv(self.abandoned) -> _ => return Err(SearchError::WorklistClosed),
+        }
+        self.peak_depth.fetch_max(sender.len(), Ordering::Relaxed);
+        Ok(())
     }
 
+    /// Returns the next job, blocking while the worklist is empty. Returns
+    /// `None` once the worklist has been closed and every job taken, or as
+    /// soon as it has been abandoned.
     pub fn next(&self) -> Option<Job> {
-        self.receiver.recv().unwrap()
+        if self.is_abandoned() {
+            return None;
+        }
+        select! {
+            recv(self.receiver) -> job => job.ok(),
+            recv(self.abandoned) -> _ => None,
+        }
     }
 
-    /// Marks the end of jobs by adding a special empty jobs to the worklist.
-    pub fn finalize(&self, num_workers: usize) {
-        for _ in 0..num_workers {
-            self.sender.send(None).unwrap();
-        }
+    /// Marks the end of jobs. Workers finish the jobs already in the
+    /// worklist, after which `next` returns `None` for every one of them.
+    pub fn close(&self) {
+        self.sender
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+    }
+
+    /// Gives up on the remaining jobs because their results can no longer be
+    /// reported, for instance after stdout was closed. Blocked and future
+    /// calls to `add` fail, and `next` returns `None`.
+    pub fn abandon(&self) {
+        self.abandon_sender
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+    }
+
+    /// Returns true once the worklist has been abandoned.
+    pub fn is_abandoned(&self) -> bool {
+        self.abandoned.try_recv() == Err(TryRecvError::Disconnected)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the most jobs that have been waiting in the worklist at once.
+    /// A peak at capacity means discovery had to wait for the workers.
+    pub fn peak_depth(&self) -> usize {
+        self.peak_depth.load(Ordering::Relaxed)
     }
 }